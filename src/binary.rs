@@ -0,0 +1,247 @@
+//! Compact binary encoding of a parsed cohort stream.
+//!
+//! `to_cg3_string` round-trips through the regex parser every time, which
+//! is wasted work once a corpus has already been analysed once and only
+//! needs to be cached or shipped to another process. `to_bytes`/`from_bytes`
+//! give consumers a fast on-disk format instead: word-forms, base-forms and
+//! tag strings are written once into a per-stream string table and
+//! referenced by index everywhere else, and cohorts/readings are framed
+//! with explicit counts so decoding never needs backtracking.
+//!
+//! The invariant that matters is `from_bytes(&to_bytes(cohorts)) ==
+//! cohorts.iter().map(OwnedCohort::from)...` (mirroring the `idempotent`
+//! text round-trip test), and that re-emitting the decoded tree with
+//! `streaming::to_cg3_string` reproduces the canonical text.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+use crate::streaming::{OwnedCohort, OwnedReading, OwnedTag};
+use crate::{Cohort, Reading, Tag};
+
+// Tag discriminants. Stored as a single byte ahead of each tag's payload.
+const TAG_POS: u8 = 0;
+const TAG_MAPPING: u8 = 1;
+const TAG_SECONDARY: u8 = 2;
+const TAG_DEPENDENCY: u8 = 3;
+const TAG_RELATION_ID: u8 = 4;
+const TAG_RELATION: u8 = 5;
+const TAG_FLAG: u8 = 6;
+const TAG_RAW: u8 = 7;
+
+struct Writer {
+    buf: Vec<u8>,
+    strings: Vec<String>,
+    indices: HashMap<String, u32>,
+}
+
+impl Writer {
+    fn new() -> Writer {
+        Writer { buf: vec![], strings: vec![], indices: HashMap::new() }
+    }
+
+    fn push_u32(&mut self, n: u32) {
+        self.buf.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn push_u8(&mut self, n: u8) {
+        self.buf.push(n);
+    }
+
+    fn intern(&mut self, s: &str) -> u32 {
+        if let Some(idx) = self.indices.get(s) {
+            return *idx;
+        }
+
+        let idx = self.strings.len() as u32;
+        self.strings.push(s.to_string());
+        self.indices.insert(s.to_string(), idx);
+        idx
+    }
+
+    fn push_tag(&mut self, tag: &Tag) {
+        match *tag {
+            Tag::Pos(s) => {
+                self.push_u8(TAG_POS);
+                let idx = self.intern(s);
+                self.push_u32(idx);
+            }
+            Tag::Mapping(s) => {
+                self.push_u8(TAG_MAPPING);
+                let idx = self.intern(s);
+                self.push_u32(idx);
+            }
+            Tag::Secondary(s) => {
+                self.push_u8(TAG_SECONDARY);
+                let idx = self.intern(s);
+                self.push_u32(idx);
+            }
+            Tag::Dependency { self_id, parent_id } => {
+                self.push_u8(TAG_DEPENDENCY);
+                self.push_u32(self_id);
+                self.push_u32(parent_id);
+            }
+            Tag::RelationId(n) => {
+                self.push_u8(TAG_RELATION_ID);
+                self.push_u32(n);
+            }
+            Tag::Relation { name, target } => {
+                self.push_u8(TAG_RELATION);
+                let idx = self.intern(name);
+                self.push_u32(idx);
+                self.push_u32(target);
+            }
+            Tag::Flag(s) => {
+                self.push_u8(TAG_FLAG);
+                let idx = self.intern(s);
+                self.push_u32(idx);
+            }
+            Tag::Raw(s) => {
+                self.push_u8(TAG_RAW);
+                let idx = self.intern(s);
+                self.push_u32(idx);
+            }
+        }
+    }
+
+    fn push_reading(&mut self, reading: &Reading) {
+        let idx = self.intern(reading.base_form());
+        self.push_u32(idx);
+
+        self.push_u32(reading.tags().len() as u32);
+        reading.tags().iter().for_each(|t| self.push_tag(t));
+
+        self.push_u32(reading.subreadings.len() as u32);
+        reading.subreadings.iter().for_each(|r| self.push_reading(r));
+    }
+
+    fn push_cohort(&mut self, cohort: &Cohort) {
+        let idx = self.intern(cohort.word_form());
+        self.push_u32(idx);
+
+        self.push_u32(cohort.tags().len() as u32);
+        cohort.tags().iter().for_each(|t| self.push_tag(t));
+
+        self.push_u32(cohort.readings.len() as u32);
+        cohort.readings.iter().for_each(|r| self.push_reading(r));
+    }
+}
+
+/// Encodes `cohorts` into the crate's compact binary format.
+pub fn to_bytes(cohorts: &[Cohort]) -> Vec<u8> {
+    let mut body = Writer::new();
+    body.push_u32(cohorts.len() as u32);
+    cohorts.iter().for_each(|c| body.push_cohort(c));
+
+    let mut out = vec![];
+    out.extend_from_slice(&(body.strings.len() as u32).to_le_bytes());
+    for s in &body.strings {
+        out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        out.extend_from_slice(s.as_bytes());
+    }
+    out.extend_from_slice(&body.buf);
+    out
+}
+
+struct Reader<'b> {
+    buf: &'b [u8],
+    pos: usize,
+    strings: Vec<String>,
+}
+
+impl<'b> Reader<'b> {
+    fn read_u32(&mut self) -> u32 {
+        let n = u32::from_le_bytes(self.buf[self.pos..self.pos + 4].try_into().unwrap());
+        self.pos += 4;
+        n
+    }
+
+    fn read_u8(&mut self) -> u8 {
+        let n = self.buf[self.pos];
+        self.pos += 1;
+        n
+    }
+
+    fn read_string(&mut self) -> String {
+        let idx = self.read_u32() as usize;
+        self.strings[idx].clone()
+    }
+
+    fn read_tag(&mut self) -> OwnedTag {
+        match self.read_u8() {
+            TAG_POS => OwnedTag::Pos(self.read_string()),
+            TAG_MAPPING => OwnedTag::Mapping(self.read_string()),
+            TAG_SECONDARY => OwnedTag::Secondary(self.read_string()),
+            TAG_DEPENDENCY => OwnedTag::Dependency { self_id: self.read_u32(), parent_id: self.read_u32() },
+            TAG_RELATION_ID => OwnedTag::RelationId(self.read_u32()),
+            TAG_RELATION => {
+                let name = self.read_string();
+                let target = self.read_u32();
+                OwnedTag::Relation { name, target }
+            }
+            TAG_FLAG => OwnedTag::Flag(self.read_string()),
+            TAG_RAW => OwnedTag::Raw(self.read_string()),
+            other => panic!("unknown tag discriminant {} in binary CG-3 stream", other)
+        }
+    }
+
+    fn read_reading(&mut self) -> OwnedReading {
+        let base_form = self.read_string().into_boxed_str();
+
+        let tag_count = self.read_u32();
+        let tags = (0..tag_count).map(|_| self.read_tag()).collect();
+
+        let subreading_count = self.read_u32();
+        let subreadings = (0..subreading_count).map(|_| self.read_reading()).collect();
+
+        OwnedReading { base_form, tags, subreadings }
+    }
+
+    fn read_cohort(&mut self) -> OwnedCohort {
+        let word_form = self.read_string().into_boxed_str();
+
+        let tag_count = self.read_u32();
+        let tags = (0..tag_count).map(|_| self.read_tag()).collect();
+
+        let reading_count = self.read_u32();
+        let readings = (0..reading_count).map(|_| self.read_reading()).collect();
+
+        OwnedCohort { word_form, tags, readings }
+    }
+}
+
+/// Decodes the output of [`to_bytes`] back into an owned cohort tree.
+pub fn from_bytes(bytes: &[u8]) -> Vec<OwnedCohort> {
+    let mut reader = Reader { buf: bytes, pos: 0, strings: vec![] };
+
+    let string_count = reader.read_u32();
+    for _ in 0..string_count {
+        let len = reader.read_u32() as usize;
+        let s = std::str::from_utf8(&reader.buf[reader.pos..reader.pos + len]).unwrap().to_string();
+        reader.pos += len;
+        reader.strings.push(s);
+    }
+
+    let cohort_count = reader.read_u32();
+    (0..cohort_count).map(|_| reader.read_cohort()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::from_string;
+
+    #[test]
+    fn round_trips_structurally_and_as_text() {
+        let stream = "\"<same>\"\n    \"sáve\" N <NomGenSg> Sem/Dummytag Sg Nom <W:21.3018> <WA:15.3018> <spelled> \"<sáve>\" @SUBJ> &SUGGESTWF &typo\n\"<.>\"\n    \".\" CLB <W:0.0> <NoSpaceAfterPunctMark>\n";
+
+        let cohorts = from_string(stream);
+        let expected: Vec<OwnedCohort> = cohorts.iter().map(OwnedCohort::from).collect();
+
+        let bytes = to_bytes(&cohorts);
+        let decoded = from_bytes(&bytes);
+
+        assert_eq!(decoded, expected);
+        assert_eq!(crate::streaming::to_cg3_string(&decoded), stream);
+    }
+}
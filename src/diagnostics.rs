@@ -0,0 +1,138 @@
+//! Error-recovering parse mode.
+//!
+//! `from_string` silently drops any line that looks like a cohort header or
+//! a reading but fails to match its regex (unbalanced `"<...>"`, a reading
+//! before any cohort, a stray bracket in a header). That's fine for
+//! well-formed corpora, but gives a tool author nothing to show a user
+//! editing CG-3 by hand. `parse_with_diagnostics` runs the same parse but
+//! collects a `Diagnostic` for every line it has to skip, and keeps going
+//! instead of losing the rest of the stream.
+
+use crate::lossless::split_lines;
+use crate::{place_reading, Cohort, Reading, RE_BASE_FORM, RE_WORD_FORM};
+
+/// A parse problem at a specific point in the source, with a rendered
+/// snippet ready to print to a terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Byte offset into the input where the offending line starts.
+    pub offset: usize,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column of the start of the underlined span.
+    pub column: usize,
+    pub message: String,
+    /// The source line followed by a `^`-underline of the offending span.
+    pub snippet: String,
+}
+
+impl Diagnostic {
+    fn new(offset: usize, line_no: usize, line_text: &str, message: impl Into<String>) -> Diagnostic {
+        let trimmed_start = line_text.len() - line_text.trim_start().len();
+        let span_len = line_text.trim().len().max(1);
+        let column = trimmed_start + 1;
+
+        let snippet = format!(
+            "{}\n{}{}",
+            line_text,
+            " ".repeat(trimmed_start),
+            "^".repeat(span_len)
+        );
+
+        Diagnostic {
+            offset,
+            line: line_no,
+            column,
+            message: message.into(),
+            snippet,
+        }
+    }
+}
+
+/// Parses `input`, recovering from any line that looks like a cohort header
+/// or reading but fails to parse: it is skipped, recorded as a `Diagnostic`,
+/// and parsing continues with the next line. A single malformed cohort
+/// never aborts parsing of the rest of the stream.
+pub fn parse_with_diagnostics<'s>(input: &'s str) -> (Vec<Cohort<'s>>, Vec<Diagnostic>) {
+    let mut cohorts: Vec<Cohort<'s>> = vec![];
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+    let mut stack: Vec<(usize, Vec<usize>)> = vec![];
+    let mut offset = 0;
+
+    for (line_no, (line, terminator)) in split_lines(input).into_iter().enumerate() {
+        let line_no = line_no + 1;
+        let trimmed = line.trim_start();
+
+        if let Some(captures) = RE_WORD_FORM.captures(line) {
+            if let Some(cohort) = Cohort::from_captures(captures) {
+                cohorts.push(cohort);
+                stack.clear();
+            }
+        } else if let Some(captures) = RE_BASE_FORM.captures(line) {
+            if let Some((indent, reading)) = Reading::from_captures(captures) {
+                match cohorts.last_mut() {
+                    Some(last_cohort) => place_reading(&mut last_cohort.readings, &mut stack, indent, reading),
+                    None => diagnostics.push(Diagnostic::new(offset, line_no, line, "reading before any cohort")),
+                }
+            }
+        } else if line == trimmed && trimmed.starts_with("\"<") {
+            // Only unindented lines can be an attempted cohort header: CG-3
+            // readings are always indented, so an indented `"<...` that
+            // failed `RE_BASE_FORM` (e.g. an unterminated `    "<x`) is a
+            // malformed *reading*, not a cohort header, even though it
+            // shares the `"<` prefix.
+            diagnostics.push(Diagnostic::new(offset, line_no, line, "malformed cohort header"));
+        } else if trimmed.starts_with('"') {
+            diagnostics.push(Diagnostic::new(offset, line_no, line, "malformed reading"));
+        }
+
+        offset += line.len() + terminator.len();
+    }
+
+    (cohorts, diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovers_from_malformed_lines() {
+        let stream = "\"<unterminated\" TAG\n\"<ok>\" TAG\n    \"ok\" N\n    \"orphan\n";
+        let (cohorts, diagnostics) = parse_with_diagnostics(stream);
+
+        assert_eq!(cohorts.len(), 1);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].line, 1);
+        assert_eq!(diagnostics[1].line, 4);
+        assert!(diagnostics[1].snippet.contains('^'));
+    }
+
+    #[test]
+    fn does_not_misflag_angle_bracket_base_forms() {
+        // Regression: a `"<"`/`"<"` cohort/reading pair whose base form is
+        // itself `<` (as in the `pathological` test fixture) must not be
+        // mistaken for a malformed cohort header just because it shares the
+        // `"<` prefix with one.
+        let stream = "\"<<>\"\n    \"<\" PUNCT LEFT <W:0.0>\n\"<>>\"\n    \">\" PUNCT LEFT <W:0.0>\n";
+        let (cohorts, diagnostics) = parse_with_diagnostics(stream);
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(cohorts.len(), 2);
+        assert_eq!(cohorts[0].readings.len(), 1);
+        assert_eq!(cohorts[1].readings.len(), 1);
+    }
+
+    #[test]
+    fn tracks_byte_offsets_across_crlf_lines() {
+        // Regression: offsets must account for the \r\n terminator
+        // split_lines reports, not a hardcoded single-byte \n.
+        let stream = "\"<a>\"\r\n    \"bad\r\n\"<b>\"\r\n    \"b\" X\r\n";
+        let (_, diagnostics) = parse_with_diagnostics(stream);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 2);
+        assert_eq!(diagnostics[0].offset, 7);
+        assert_eq!(&stream[diagnostics[0].offset..], "    \"bad\r\n\"<b>\"\r\n    \"b\" X\r\n");
+    }
+}
@@ -3,28 +3,122 @@ use regex::Regex;
 use serde_derive::Serialize;
 
 lazy_static! {
-    static ref RE_WORD_FORM: Regex = Regex::new(r#"^"<(.*?)>"(?:\s(.*)?)?$"#).unwrap();
-    static ref RE_BASE_FORM: Regex = Regex::new(r#"^\s+"(.*?)"(?:\s(.*)?)?$"#).unwrap();
+    pub(crate) static ref RE_WORD_FORM: Regex = Regex::new(r#"^"<(.*?)>"(?:\s(.*)?)?$"#).unwrap();
+    pub(crate) static ref RE_BASE_FORM: Regex = Regex::new(r#"^(\s+)"(.*?)"(?:\s(.*)?)?$"#).unwrap();
+}
+
+pub mod binary;
+pub mod diagnostics;
+pub mod lossless;
+pub mod streaming;
+
+pub use diagnostics::{parse_with_diagnostics, Diagnostic};
+
+/// A single CG-3 tag, typed by its syntax so consumers don't have to
+/// re-parse `@`/`&`/`<...>`/`ID:`/`R:`/`#x->y` conventions themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Tag<'s> {
+    /// A plain tag with no special prefix, e.g. a part-of-speech or
+    /// grammatical tag (`N`, `NOM`, `SG`).
+    Pos(&'s str),
+    /// A mapping tag (`@SUBJ>`), stored without the leading `@`.
+    Mapping(&'s str),
+    /// A secondary/trace tag (`<spelled>`, `<W:0.0>`), stored without the
+    /// angle brackets.
+    Secondary(&'s str),
+    /// A dependency edge (`#self->parent`).
+    Dependency { self_id: u32, parent_id: u32 },
+    /// A relation graph node id (`ID:n`).
+    RelationId(u32),
+    /// A named relation to another node (`R:NAME:n`).
+    Relation { name: &'s str, target: u32 },
+    /// A link/suggestion flag (`&LINK`), stored without the leading `&`.
+    Flag(&'s str),
+    /// Anything that looks like a typed tag but doesn't parse cleanly, kept
+    /// verbatim so nothing is ever lost.
+    Raw(&'s str),
+}
+
+impl<'s> Tag<'s> {
+    fn parse(s: &'s str) -> Tag<'s> {
+        if let Some(rest) = s.strip_prefix('@') {
+            return Tag::Mapping(rest);
+        }
+
+        if let Some(rest) = s.strip_prefix('&') {
+            return Tag::Flag(rest);
+        }
+
+        if s.len() >= 2 && s.starts_with('<') && s.ends_with('>') {
+            return Tag::Secondary(&s[1..s.len() - 1]);
+        }
+
+        if let Some(rest) = s.strip_prefix("ID:") {
+            return match rest.parse() {
+                Ok(n) => Tag::RelationId(n),
+                Err(_) => Tag::Raw(s)
+            };
+        }
+
+        if let Some(rest) = s.strip_prefix("R:") {
+            return match rest.rsplit_once(':').and_then(|(name, target)| Some((name, target.parse().ok()?))) {
+                Some((name, target)) => Tag::Relation { name, target },
+                None => Tag::Raw(s)
+            };
+        }
+
+        if let Some(rest) = s.strip_prefix('#') {
+            return match rest.split_once("->").and_then(|(self_id, parent_id)| Some((self_id.parse().ok()?, parent_id.parse().ok()?))) {
+                Some((self_id, parent_id)) => Tag::Dependency { self_id, parent_id },
+                None => Tag::Raw(s)
+            };
+        }
+
+        Tag::Pos(s)
+    }
+
+    pub fn to_string(&self) -> String {
+        match self {
+            Tag::Pos(s) => s.to_string(),
+            Tag::Mapping(s) => format!("@{}", s),
+            Tag::Secondary(s) => format!("<{}>", s),
+            Tag::Dependency { self_id, parent_id } => format!("#{}->{}", self_id, parent_id),
+            Tag::RelationId(n) => format!("ID:{}", n),
+            Tag::Relation { name, target } => format!("R:{}:{}", name, target),
+            Tag::Flag(s) => format!("&{}", s),
+            Tag::Raw(s) => s.to_string()
+        }
+    }
+
+    fn dependency_parent(&self) -> Option<u32> {
+        match self {
+            Tag::Dependency { parent_id, .. } => Some(*parent_id),
+            _ => None
+        }
+    }
+}
+
+fn parse_tags<'s>(raw: Option<regex::Match<'s>>) -> Vec<Tag<'s>> {
+    raw.filter(|x| x.as_str() != "")
+        .map(|x| x.as_str().split(" ").map(Tag::parse).collect::<Vec<_>>())
+        .unwrap_or(vec![])
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Cohort<'s> {
     word_form: &'s str,
-    tags: Vec<&'s str>,
-    readings: Vec<Reading<'s>>,
+    tags: Vec<Tag<'s>>,
+    pub(crate) readings: Vec<Reading<'s>>,
 }
 
 impl<'s> Cohort<'s> {
-    fn from_captures(captures: regex::Captures<'s>) -> Option<Cohort<'s>> {
+    pub(crate) fn from_captures(captures: regex::Captures<'s>) -> Option<Cohort<'s>> {
         let word_form = match captures.get(1) {
             Some(v) => v.as_str(),
             None => return None
         };
 
-        let tags = captures.get(2)
-            .filter(|x| x.as_str() != "")
-            .map(|x| x.as_str().split(" ").collect::<Vec<_>>())
-            .unwrap_or(vec![]);
+        let tags = parse_tags(captures.get(2));
 
         Some(Cohort {
             word_form,
@@ -40,73 +134,166 @@ impl<'s> Cohort<'s> {
         s.push_str(">\"");
         self.tags.iter().for_each(|t| {
             s.push_str(" ");
-            s.push_str(t);
+            s.push_str(&t.to_string());
         });
         s.push_str("\n");
-        self.readings.iter().for_each(|r| {
-            s.push_str("    \"");
-            s.push_str(r.base_form);
-            s.push_str("\"");
-            r.tags.iter().for_each(|t| {
-                s.push_str(" ");
-                s.push_str(t);
-            });
-            s.push_str("\n");
-        });
+        self.readings.iter().for_each(|r| r.write_to(&mut s, 1));
         s
     }
+
+    /// The dependency parent id from a `#self->parent` tag on this cohort
+    /// itself or, failing that, on its first reading. Convenience for graph
+    /// consumers walking a disambiguated stream.
+    pub fn dependency_head(&self) -> Option<u32> {
+        self.tags.iter().find_map(Tag::dependency_parent)
+            .or_else(|| self.readings.first().and_then(Reading::dependency_head))
+    }
+
+    pub(crate) fn word_form(&self) -> &'s str {
+        self.word_form
+    }
+
+    pub(crate) fn tags(&self) -> &[Tag<'s>] {
+        &self.tags
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Reading<'s> {
     base_form: &'s str,
-    tags: Vec<&'s str>,
+    tags: Vec<Tag<'s>>,
+    pub(crate) subreadings: Vec<Reading<'s>>,
 }
 
 impl<'s> Reading<'s> {
-    fn from_captures(captures: regex::Captures<'s>) -> Option<Reading<'s>> {
-        let base_form = match captures.get(1) {
+    /// Returns the indentation length alongside the parsed reading so the
+    /// caller can place it in the subreading tree.
+    pub(crate) fn from_captures(captures: regex::Captures<'s>) -> Option<(usize, Reading<'s>)> {
+        let indent = match captures.get(1) {
+            Some(v) => v.as_str().len(),
+            None => return None
+        };
+
+        let base_form = match captures.get(2) {
             Some(v) => v.as_str(),
             None => return None
         };
 
-        let tags = captures.get(2)
-            .filter(|x| x.as_str() != "")
-            .map(|x| x.as_str().split(" ").collect::<Vec<_>>())
-            .unwrap_or(vec![]);
+        let tags = parse_tags(captures.get(3));
 
-        Some(Reading {
+        Some((indent, Reading {
             base_form,
-            tags
-        })
+            tags,
+            subreadings: vec![]
+        }))
+    }
+
+    /// Emits this reading at `depth` levels of four-space indentation, each
+    /// subreading following its parent one level deeper.
+    fn write_to(&self, s: &mut String, depth: usize) {
+        s.push_str(&"    ".repeat(depth));
+        s.push_str("\"");
+        s.push_str(self.base_form);
+        s.push_str("\"");
+        self.tags.iter().for_each(|t| {
+            s.push_str(" ");
+            s.push_str(&t.to_string());
+        });
+        s.push_str("\n");
+        self.subreadings.iter().for_each(|r| r.write_to(s, depth + 1));
+    }
+
+    /// This reading's dependency parent id, from a `#self->parent` tag.
+    pub fn dependency_head(&self) -> Option<u32> {
+        self.tags.iter().find_map(Tag::dependency_parent)
+    }
+
+    pub(crate) fn base_form(&self) -> &'s str {
+        self.base_form
+    }
+
+    pub(crate) fn tags(&self) -> &[Tag<'s>] {
+        &self.tags
     }
 }
 
+/// A tree node shaped like `Reading`: some payload plus a `subreadings` vec
+/// of the same type. Lets the indent-stack placement logic in
+/// `place_reading` work over both the borrowed `Reading` tree and the
+/// owned `streaming::OwnedReading` tree.
+pub(crate) trait Nested: Sized {
+    fn subreadings_mut(&mut self) -> &mut Vec<Self>;
+}
+
+impl<'s> Nested for Reading<'s> {
+    fn subreadings_mut(&mut self) -> &mut Vec<Self> {
+        &mut self.subreadings
+    }
+}
+
+/// Walks `path` through nested `subreadings` vecs, returning the vec at that
+/// location. An empty path is the tree's top-level vec.
+pub(crate) fn subreadings_at_mut<'a, T: Nested>(readings: &'a mut Vec<T>, path: &[usize]) -> &'a mut Vec<T> {
+    match path {
+        [] => readings,
+        [idx, rest @ ..] => subreadings_at_mut(readings[*idx].subreadings_mut(), rest)
+    }
+}
+
+/// Places `reading` into the tree rooted at `readings` according to its
+/// `indent`, updating `stack` — the (indent, path) frames tracking the
+/// current chain of subreadings, innermost last — to match. A reading
+/// indented more deeply than the frame on top of `stack` becomes that
+/// frame's child; otherwise frames are popped until a shallower one
+/// remains (or the stack empties), so a subreading with no parent at its
+/// exact depth attaches to the nearest shallower reading instead of being
+/// dropped or panicking.
+pub(crate) fn place_reading<T: Nested>(readings: &mut Vec<T>, stack: &mut Vec<(usize, Vec<usize>)>, indent: usize, reading: T) {
+    while matches!(stack.last(), Some((top_indent, _)) if indent <= *top_indent) {
+        stack.pop();
+    }
+
+    let parent_path: Vec<usize> = stack.last().map(|(_, path)| path.clone()).unwrap_or_default();
+    let target = subreadings_at_mut(readings, &parent_path);
+    target.push(reading);
+
+    let mut path = parent_path;
+    path.push(target.len() - 1);
+    stack.push((indent, path));
+}
+
 pub fn from_string<'s>(input: &'s str) -> Vec<Cohort<'s>> {
-    input.lines().fold(vec![], |mut state, line| {
+    let mut cohorts: Vec<Cohort<'s>> = vec![];
+    // Stack of (indent, path) frames tracking the current chain of
+    // subreadings, innermost last. `path` addresses the frame's own
+    // reading via `subreadings_at_mut`.
+    let mut stack: Vec<(usize, Vec<usize>)> = vec![];
+
+    for line in input.lines() {
         if let Some(captures) = RE_WORD_FORM.captures(line) {
             if let Some(cohort) = Cohort::from_captures(captures) {
-                state.push(cohort);
+                cohorts.push(cohort);
+                stack.clear();
             }
-            
-            return state;
+
+            continue;
         }
 
-        let last_cohort = match state.last_mut() {
+        let last_cohort = match cohorts.last_mut() {
             Some(v) => v,
-            None => return state
+            None => continue
         };
 
         if let Some(captures) = RE_BASE_FORM.captures(line) {
-            if let Some(reading) = Reading::from_captures(captures) {
-                last_cohort.readings.push(reading);
+            if let Some((indent, reading)) = Reading::from_captures(captures) {
+                place_reading(&mut last_cohort.readings, &mut stack, indent, reading);
             }
-            
-            return state;
+
+            continue;
         }
+    }
 
-        state
-    })
+    cohorts
 }
 
 pub fn to_cg3_string<'s>(input: &[Cohort<'s>]) -> String {
@@ -236,4 +423,65 @@ garbage
         println!("{}", s);
         assert_eq!(stream, &s);
     }
+
+    #[test]
+    fn nested_subreadings_with_dedent_and_orphan_indent() {
+        // Indents (in spaces): PAST=4, DER1=8, DER2=12, ORPHAN=10, ALT=4,
+        // DER3=8. ORPHAN's indent doesn't match any open frame exactly (it's
+        // deeper than DER1's 8 but shallower than DER2's 12), so it can't
+        // nest under DER2 — the stack pops DER2's frame and ORPHAN attaches
+        // to the nearest shallower reading still open, DER1, as its second
+        // child rather than being dropped.
+        let stream = "\"<went>\"
+    \"go\" V PAST
+        \"go\" DER1
+            \"go\" DER2
+          \"go\" ORPHAN
+    \"go\" ALT
+        \"go\" DER3
+";
+
+        let cohorts = from_string(stream);
+        assert_eq!(cohorts.len(), 1);
+
+        let readings = &cohorts[0].readings;
+        // Two top-level readings: "go"/PAST, "go"/ALT.
+        assert_eq!(readings.len(), 2);
+
+        // DER1 has two children: DER2, then ORPHAN reparented alongside it.
+        assert_eq!(readings[0].subreadings.len(), 1);
+        let der1 = &readings[0].subreadings[0];
+        assert_eq!(der1.subreadings.len(), 2);
+        assert_eq!(der1.subreadings[0].tags()[0], Tag::Pos("DER2"));
+        assert_eq!(der1.subreadings[1].tags()[0], Tag::Pos("ORPHAN"));
+
+        // Dedenting back to one level attaches "go"/DER3 as a child of ALT,
+        // not of DER1 or DER2.
+        assert_eq!(readings[1].subreadings.len(), 1);
+        assert_eq!(readings[1].subreadings[0].tags()[0], Tag::Pos("DER3"));
+
+        // `to_cg3_string` always re-indents to 4 spaces per depth, so its
+        // output won't be byte-identical to ORPHAN's non-canonical 10-space
+        // input indent — but re-parsing that output must reproduce the same
+        // recovered tree shape.
+        let s = to_cg3_string(&cohorts);
+        let reparsed = from_string(&s);
+        assert_eq!(reparsed[0].readings.len(), 2);
+        assert_eq!(reparsed[0].readings[0].subreadings[0].subreadings.len(), 2);
+    }
+
+    #[test]
+    fn dependency_tag_round_trips_and_resolves() {
+        let stream = "\"<went>\"\n    \"go\" V PAST #2->1\n";
+        let cohorts = from_string(stream);
+
+        let reading = &cohorts[0].readings[0];
+        assert_eq!(reading.tags()[2], Tag::Dependency { self_id: 2, parent_id: 1 });
+        assert_eq!(reading.tags()[2].to_string(), "#2->1");
+        assert_eq!(reading.dependency_head(), Some(1));
+        assert_eq!(cohorts[0].dependency_head(), Some(1));
+
+        let s = to_cg3_string(&cohorts);
+        assert_eq!(stream, &s);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,209 @@
+//! Lossless parse mode.
+//!
+//! `from_string`/`to_cg3_string` reconstruct text from the structured
+//! `Cohort`/`Reading` model, which only round-trips input that already uses
+//! single-space tag separators and has no comments or stray lines. Real CG-3
+//! streams are messier: `:` cohort separators, `# ...` comments, arbitrary
+//! whitespace, CRLF line endings, a missing final newline, and tool-generated
+//! garbage all show up between cohorts. This module keeps every source line
+//! borrowed verbatim (no string copies, though the line stream itself grows
+//! with the input) alongside whatever structure could be recovered from it, and keeps
+//! each line's own terminator alongside it, so `to_cg3_string` reproduces
+//! the original byte for byte no matter what was in it.
+
+use crate::{place_reading, Cohort, Reading, RE_BASE_FORM, RE_WORD_FORM};
+
+/// One line of source, tagged by what it represents.
+///
+/// Every variant carries the original line text (without its terminator —
+/// see `Line`) so serialization never has to reconstruct formatting the
+/// parser didn't understand.
+#[derive(Debug, Clone)]
+pub enum Token<'s> {
+    /// A `"<word>" TAGS` cohort header.
+    WordForm(&'s str, Cohort<'s>),
+    /// An indented `"base"` reading line, with its raw indent depth.
+    Reading(&'s str, usize, Reading<'s>),
+    /// A `# ...` comment line.
+    Comment(&'s str),
+    /// A `:` cohort separator line.
+    Separator(&'s str),
+    /// A blank (whitespace-only) line.
+    Blank(&'s str),
+    /// Anything else: stray garbage, tool output, malformed lines.
+    Unknown(&'s str),
+}
+
+impl<'s> Token<'s> {
+    /// The original source text for this line, not including its
+    /// terminator.
+    pub fn raw(&self) -> &'s str {
+        match self {
+            Token::WordForm(raw, _) => raw,
+            Token::Reading(raw, _, _) => raw,
+            Token::Comment(raw) => raw,
+            Token::Separator(raw) => raw,
+            Token::Blank(raw) => raw,
+            Token::Unknown(raw) => raw,
+        }
+    }
+}
+
+/// A source line paired with the exact terminator that followed it: `"\n"`,
+/// `"\r\n"`, or `""` for a final line with no trailing newline. Keeping this
+/// per-line (rather than assuming one uniform style for the whole stream)
+/// is what lets `to_cg3_string` reproduce arbitrary input byte for byte.
+#[derive(Debug, Clone)]
+pub struct Line<'s> {
+    pub token: Token<'s>,
+    terminator: &'s str,
+}
+
+/// Splits `input` into `(line, terminator)` pairs without normalizing or
+/// discarding the terminator the way `str::lines` does.
+pub(crate) fn split_lines(input: &str) -> Vec<(&str, &str)> {
+    let mut lines = vec![];
+    let mut rest = input;
+
+    while !rest.is_empty() {
+        match rest.find('\n') {
+            Some(idx) => {
+                let (line, after) = (&rest[..idx], &rest[idx + 1..]);
+                if let Some(line) = line.strip_suffix('\r') {
+                    lines.push((line, "\r\n"));
+                } else {
+                    lines.push((line, "\n"));
+                }
+                rest = after;
+            }
+            None => {
+                lines.push((rest, ""));
+                rest = "";
+            }
+        }
+    }
+
+    lines
+}
+
+fn classify(line: &str) -> Token<'_> {
+    if let Some(captures) = RE_WORD_FORM.captures(line) {
+        if let Some(cohort) = Cohort::from_captures(captures) {
+            return Token::WordForm(line, cohort);
+        }
+    }
+
+    if let Some(captures) = RE_BASE_FORM.captures(line) {
+        if let Some((indent, reading)) = Reading::from_captures(captures) {
+            return Token::Reading(line, indent, reading);
+        }
+    }
+
+    let trimmed = line.trim_start();
+    if line.trim().is_empty() {
+        Token::Blank(line)
+    } else if trimmed.starts_with('#') {
+        Token::Comment(line)
+    } else if trimmed.starts_with(':') {
+        Token::Separator(line)
+    } else {
+        Token::Unknown(line)
+    }
+}
+
+/// Parses `input` into a line stream that retains every line, its
+/// terminator, and in order.
+pub fn from_string<'s>(input: &'s str) -> Vec<Line<'s>> {
+    split_lines(input).into_iter().map(|(line, terminator)| Line {
+        token: classify(line),
+        terminator
+    }).collect()
+}
+
+/// Re-emits `lines` as the exact original source text.
+pub fn to_cg3_string(lines: &[Line]) -> String {
+    let mut s = String::new();
+    lines.iter().for_each(|l| {
+        s.push_str(l.token.raw());
+        s.push_str(l.terminator);
+    });
+    s
+}
+
+/// Recovers the structured `Cohort` tree from a line stream, the same shape
+/// `crate::from_string` produces, discarding comments/separators/blanks/
+/// unknown lines along the way.
+pub fn to_cohorts<'s>(lines: &[Line<'s>]) -> Vec<Cohort<'s>> {
+    let mut cohorts: Vec<Cohort<'s>> = vec![];
+    let mut stack: Vec<(usize, Vec<usize>)> = vec![];
+
+    for line in lines {
+        match &line.token {
+            Token::WordForm(_, cohort) => {
+                cohorts.push(cohort.clone());
+                stack.clear();
+            }
+            Token::Reading(_, indent, reading) => {
+                let last_cohort = match cohorts.last_mut() {
+                    Some(v) => v,
+                    None => continue
+                };
+
+                place_reading(&mut last_cohort.readings, &mut stack, *indent, reading.clone());
+            }
+            Token::Comment(_) | Token::Separator(_) | Token::Blank(_) | Token::Unknown(_) => {}
+        }
+    }
+
+    cohorts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idempotent_with_comments_and_garbage() {
+        let stream = "\
+some garbage
+\"<They>\" TAG1 TAG2
+    \"they\" <*>  PRON  PERS NOM PL3 SUBJ
+# a comment
+\"<went>\"
+    \"go\" V PAST VFIN
+:
+\"<to>\"
+    \"to\" PREP
+";
+
+        let lines = from_string(stream);
+        let s = to_cg3_string(&lines);
+        assert_eq!(stream, &s);
+
+        let cohorts = to_cohorts(&lines);
+        assert_eq!(cohorts.len(), 3);
+    }
+
+    #[test]
+    fn idempotent_without_trailing_newline() {
+        let stream = "\"<went>\"\n    \"go\" V PAST VFIN";
+
+        let lines = from_string(stream);
+        let s = to_cg3_string(&lines);
+        assert_eq!(stream, &s);
+        assert!(!s.ends_with('\n'));
+    }
+
+    #[test]
+    fn idempotent_with_crlf() {
+        let stream = "\"<went>\"\r\n    \"go\" V PAST VFIN\r\n";
+
+        let lines = from_string(stream);
+        let s = to_cg3_string(&lines);
+        assert_eq!(stream, &s);
+
+        let cohorts = to_cohorts(&lines);
+        assert_eq!(cohorts.len(), 1);
+        assert_eq!(cohorts[0].readings[0].base_form(), "go");
+    }
+}
@@ -0,0 +1,267 @@
+//! True streaming parse mode.
+//!
+//! `from_string` buffers the whole input and returns every `Cohort`
+//! up front, which is fine for a sentence but not for a gigabyte analyser
+//! corpus. `CohortReader` instead reads one line at a time from any
+//! `BufRead`, emitting each cohort as soon as the next `"<...>"` line (or
+//! EOF) closes it off, so memory stays bounded by a single cohort rather
+//! than the whole stream.
+//!
+//! Because `Cohort`/`Reading` borrow from the input they were parsed from,
+//! and a streaming reader has no stable buffer to borrow from across
+//! iterations, this module produces an owned parallel tree instead:
+//! `OwnedCohort`/`OwnedReading`/`OwnedTag` hold `Box<str>`/`String` rather
+//! than `&str`. Each is a cheap `From` conversion away from the borrowed
+//! form, so the existing `from_string` API is unaffected.
+
+use std::io::BufRead;
+
+use crate::{place_reading, Cohort, Nested, Reading, Tag, RE_BASE_FORM, RE_WORD_FORM};
+
+/// Owned counterpart of [`crate::Tag`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OwnedTag {
+    Pos(String),
+    Mapping(String),
+    Secondary(String),
+    Dependency { self_id: u32, parent_id: u32 },
+    RelationId(u32),
+    Relation { name: String, target: u32 },
+    Flag(String),
+    Raw(String),
+}
+
+impl<'s> From<&Tag<'s>> for OwnedTag {
+    fn from(tag: &Tag<'s>) -> OwnedTag {
+        match tag {
+            Tag::Pos(s) => OwnedTag::Pos(s.to_string()),
+            Tag::Mapping(s) => OwnedTag::Mapping(s.to_string()),
+            Tag::Secondary(s) => OwnedTag::Secondary(s.to_string()),
+            Tag::Dependency { self_id, parent_id } => OwnedTag::Dependency { self_id: *self_id, parent_id: *parent_id },
+            Tag::RelationId(n) => OwnedTag::RelationId(*n),
+            Tag::Relation { name, target } => OwnedTag::Relation { name: name.to_string(), target: *target },
+            Tag::Flag(s) => OwnedTag::Flag(s.to_string()),
+            Tag::Raw(s) => OwnedTag::Raw(s.to_string())
+        }
+    }
+}
+
+impl OwnedTag {
+    pub fn to_string(&self) -> String {
+        match self {
+            OwnedTag::Pos(s) => s.clone(),
+            OwnedTag::Mapping(s) => format!("@{}", s),
+            OwnedTag::Secondary(s) => format!("<{}>", s),
+            OwnedTag::Dependency { self_id, parent_id } => format!("#{}->{}", self_id, parent_id),
+            OwnedTag::RelationId(n) => format!("ID:{}", n),
+            OwnedTag::Relation { name, target } => format!("R:{}:{}", name, target),
+            OwnedTag::Flag(s) => format!("&{}", s),
+            OwnedTag::Raw(s) => s.clone()
+        }
+    }
+}
+
+/// Owned counterpart of [`crate::Reading`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedReading {
+    pub base_form: Box<str>,
+    pub tags: Vec<OwnedTag>,
+    pub subreadings: Vec<OwnedReading>,
+}
+
+impl<'s> From<&Reading<'s>> for OwnedReading {
+    fn from(reading: &Reading<'s>) -> OwnedReading {
+        OwnedReading {
+            base_form: reading.base_form().into(),
+            tags: reading.tags().iter().map(OwnedTag::from).collect(),
+            subreadings: reading.subreadings.iter().map(OwnedReading::from).collect()
+        }
+    }
+}
+
+impl Nested for OwnedReading {
+    fn subreadings_mut(&mut self) -> &mut Vec<Self> {
+        &mut self.subreadings
+    }
+}
+
+impl OwnedReading {
+    fn write_to(&self, s: &mut String, depth: usize) {
+        s.push_str(&"    ".repeat(depth));
+        s.push('"');
+        s.push_str(&self.base_form);
+        s.push('"');
+        self.tags.iter().for_each(|t| {
+            s.push(' ');
+            s.push_str(&t.to_string());
+        });
+        s.push('\n');
+        self.subreadings.iter().for_each(|r| r.write_to(s, depth + 1));
+    }
+}
+
+/// Owned counterpart of [`crate::Cohort`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedCohort {
+    pub word_form: Box<str>,
+    pub tags: Vec<OwnedTag>,
+    pub readings: Vec<OwnedReading>,
+}
+
+impl<'s> From<&Cohort<'s>> for OwnedCohort {
+    fn from(cohort: &Cohort<'s>) -> OwnedCohort {
+        OwnedCohort {
+            word_form: cohort.word_form().into(),
+            tags: cohort.tags().iter().map(OwnedTag::from).collect(),
+            readings: cohort.readings.iter().map(OwnedReading::from).collect()
+        }
+    }
+}
+
+impl OwnedCohort {
+    pub fn to_string(&self) -> String {
+        let mut s = String::new();
+        s.push_str("\"<");
+        s.push_str(&self.word_form);
+        s.push_str(">\"");
+        self.tags.iter().for_each(|t| {
+            s.push(' ');
+            s.push_str(&t.to_string());
+        });
+        s.push('\n');
+        self.readings.iter().for_each(|r| r.write_to(&mut s, 1));
+        s
+    }
+}
+
+/// The owned-tree equivalent of `crate::to_cg3_string`.
+pub fn to_cg3_string(input: &[OwnedCohort]) -> String {
+    input.iter().map(OwnedCohort::to_string).collect::<Vec<_>>().join("")
+}
+
+/// The only way `CohortReader` can fail: the underlying reader errored.
+#[derive(Debug)]
+pub struct ParseError(std::io::Error);
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "error reading CG-3 stream: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+impl From<std::io::Error> for ParseError {
+    fn from(err: std::io::Error) -> ParseError {
+        ParseError(err)
+    }
+}
+
+/// Iterates over the cohorts of a CG-3 stream read from `R`, one line at a
+/// time, so memory stays bounded by a single cohort regardless of input
+/// size.
+pub struct CohortReader<R> {
+    reader: R,
+    /// A word-form line read while closing off the previous cohort, not yet
+    /// consumed by the next `next()` call.
+    pending: Option<String>,
+    done: bool,
+}
+
+impl<R: BufRead> CohortReader<R> {
+    pub fn new(reader: R) -> CohortReader<R> {
+        CohortReader { reader, pending: None, done: false }
+    }
+
+    fn next_line(&mut self) -> std::io::Result<Option<String>> {
+        if let Some(line) = self.pending.take() {
+            return Ok(Some(line));
+        }
+
+        let mut buf = String::new();
+        if self.reader.read_line(&mut buf)? == 0 {
+            return Ok(None);
+        }
+
+        while buf.ends_with('\n') || buf.ends_with('\r') {
+            buf.pop();
+        }
+
+        Ok(Some(buf))
+    }
+}
+
+impl<R: BufRead> Iterator for CohortReader<R> {
+    type Item = Result<OwnedCohort, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut cohort: Option<OwnedCohort> = None;
+        let mut stack: Vec<(usize, Vec<usize>)> = vec![];
+
+        loop {
+            let line = match self.next_line() {
+                Ok(Some(line)) => line,
+                Ok(None) => {
+                    self.done = true;
+                    break;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+            };
+
+            if let Some(captures) = RE_WORD_FORM.captures(&line) {
+                if cohort.is_some() {
+                    self.pending = Some(line);
+                    break;
+                }
+
+                if let Some(parsed) = Cohort::from_captures(captures) {
+                    cohort = Some(OwnedCohort::from(&parsed));
+                }
+
+                continue;
+            }
+
+            let cur = match cohort.as_mut() {
+                Some(v) => v,
+                None => continue
+            };
+
+            if let Some(captures) = RE_BASE_FORM.captures(&line) {
+                if let Some((indent, reading)) = Reading::from_captures(captures) {
+                    let owned_reading = OwnedReading::from(&reading);
+                    place_reading(&mut cur.readings, &mut stack, indent, owned_reading);
+                }
+            }
+        }
+
+        cohort.map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_cohorts_one_at_a_time() {
+        let stream = "\"<They>\" TAG1\n    \"they\" PRON\n\"<went>\"\n    \"go\" V PAST\n        \"go\" DER\n";
+        let reader = CohortReader::new(stream.as_bytes());
+        let cohorts = reader.collect::<Result<Vec<_>, _>>().unwrap();
+
+        assert_eq!(cohorts.len(), 2);
+        assert_eq!(&*cohorts[0].word_form, "They");
+        assert_eq!(&*cohorts[1].readings[0].base_form, "go");
+        assert_eq!(cohorts[1].readings[0].subreadings.len(), 1);
+    }
+}